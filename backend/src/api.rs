@@ -0,0 +1,135 @@
+
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Read-only HTTP endpoints for wallets, transactions, and pending
+//! escrows/HTLCs.
+
+use exonum::api::{self, ServiceApiBuilder, ServiceApiState};
+use exonum::crypto::{Hash, PublicKey};
+use exonum::explorer::{BlockchainExplorer, TransactionInfo};
+
+use schema::CurrencySchema;
+use transactions::native_token;
+
+/// A wallet's balance, frozen balance, and the ordered history of
+/// transaction hashes that touched it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WalletInfo {
+    pub balance: u64,
+    pub frozen_balance: u64,
+    pub history: Vec<Hash>,
+}
+
+/// An outstanding escrow or HTLC in which the queried wallet is either the
+/// sender whose funds are frozen or the recipient.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingLock {
+    pub tx_hash: Hash,
+    pub counterparty: PublicKey,
+    pub amount: u64,
+    pub deadline: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PubKeyQuery {
+    pub pub_key: PublicKey,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TxHashQuery {
+    pub tx_hash: Hash,
+}
+
+pub struct CryptocurrencyApi;
+
+impl CryptocurrencyApi {
+    /// `GET v1/wallet/info?pub_key=...` — balance, frozen balance, and
+    /// transaction history for a wallet.
+    fn wallet_info(state: &ServiceApiState, query: PubKeyQuery) -> api::Result<WalletInfo> {
+        let snapshot = state.snapshot();
+        let schema = CurrencySchema::new(&snapshot);
+        let token_id = native_token();
+
+        schema
+            .wallet(&query.pub_key)
+            .map(|_wallet| WalletInfo {
+                balance: schema.wallet_balance(&query.pub_key, &token_id),
+                frozen_balance: schema.wallet_frozen_balance(&query.pub_key, &token_id),
+                history: schema.wallet_history(&query.pub_key),
+            })
+            .ok_or_else(|| api::Error::NotFound("Wallet not found".to_owned()))
+    }
+
+    /// `GET v1/transactions?tx_hash=...` — the transaction decoded from the
+    /// pool or the committed block it landed in, whichever applies.
+    fn transaction_info(
+        state: &ServiceApiState,
+        query: TxHashQuery,
+    ) -> api::Result<TransactionInfo> {
+        BlockchainExplorer::new(state.blockchain())
+            .transaction(&query.tx_hash)
+            .ok_or_else(|| api::Error::NotFound("Transaction not found".to_owned()))
+    }
+
+    /// `GET v1/wallet/pending?pub_key=...` — unsettled escrows and HTLCs in
+    /// which this wallet is the sender or the recipient, so a client can
+    /// reconcile frozen funds without replaying the whole blockchain.
+    fn pending_locks(state: &ServiceApiState, query: PubKeyQuery) -> api::Result<Vec<PendingLock>> {
+        let snapshot = state.snapshot();
+        let schema = CurrencySchema::new(&snapshot);
+
+        let mut locks: Vec<PendingLock> = schema
+            .pending_escrows(&query.pub_key)
+            .into_iter()
+            .map(|escrow| {
+                let counterparty = if escrow.to() == &query.pub_key {
+                    *escrow.sender()
+                } else {
+                    *escrow.to()
+                };
+                PendingLock {
+                    tx_hash: *escrow.tx_hash(),
+                    counterparty,
+                    amount: escrow.amount(),
+                    deadline: escrow.deadline(),
+                }
+            })
+            .collect();
+
+        locks.extend(schema.pending_htlcs(&query.pub_key).into_iter().map(|htlc| {
+            let counterparty = if htlc.to() == &query.pub_key {
+                *htlc.from()
+            } else {
+                *htlc.to()
+            };
+            PendingLock {
+                tx_hash: *htlc.tx_hash(),
+                counterparty,
+                amount: htlc.amount(),
+                deadline: htlc.expiry(),
+            }
+        }));
+
+        Ok(locks)
+    }
+
+    pub fn wire(builder: &mut ServiceApiBuilder) {
+        builder
+            .public_scope()
+            .endpoint("v1/wallet/info", Self::wallet_info)
+            .endpoint("v1/transactions", Self::transaction_info)
+            .endpoint("v1/wallet/pending", Self::pending_locks);
+    }
+}
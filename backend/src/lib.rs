@@ -0,0 +1,74 @@
+
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Cryptocurrency service: wallets, transfers, and the various conditional
+//! locks (HTLCs, mail escrows, oracle-settled contracts) built on top of
+//! them.
+
+#[macro_use]
+extern crate exonum;
+#[macro_use]
+extern crate exonum_derive;
+#[macro_use]
+extern crate failure;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+
+pub mod api;
+pub mod proto;
+pub mod schema;
+pub mod transactions;
+
+use exonum::api::ServiceApiBuilder;
+use exonum::blockchain::{Service, Transaction};
+use exonum::crypto::Hash;
+use exonum::encoding::Error as EncodingError;
+use exonum::messages::RawTransaction;
+use exonum::storage::Snapshot;
+
+use transactions::WalletTransactions;
+
+/// Unique service identifier.
+pub const CRYPTOCURRENCY_SERVICE_ID: u16 = 128;
+/// Unique service name.
+pub const SERVICE_NAME: &str = "cryptocurrency";
+
+#[derive(Debug)]
+pub struct CurrencyService;
+
+impl Service for CurrencyService {
+    fn service_id(&self) -> u16 {
+        CRYPTOCURRENCY_SERVICE_ID
+    }
+
+    fn service_name(&self) -> &str {
+        SERVICE_NAME
+    }
+
+    fn state_hash(&self, snapshot: &Snapshot) -> Vec<Hash> {
+        schema::CurrencySchema::new(snapshot).state_hash()
+    }
+
+    fn tx_from_raw(&self, raw: RawTransaction) -> Result<Box<dyn Transaction>, EncodingError> {
+        let tx = WalletTransactions::tx_from_raw(raw)?;
+        Ok(tx.into())
+    }
+
+    fn wire_api(&self, builder: &mut ServiceApiBuilder) {
+        api::CryptocurrencyApi::wire(builder);
+    }
+}
@@ -0,0 +1,8 @@
+//! Generated Protobuf structs for wallet transactions. The `ProtobufConvert`
+//! impls that wire them to the transaction types are derived in
+//! `transactions`.
+
+#![allow(bare_trait_objects)]
+#![allow(renamed_and_removed_lints)]
+
+include!(concat!(env!("OUT_DIR"), "/protobuf_mod.rs"));
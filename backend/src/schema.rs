@@ -0,0 +1,471 @@
+
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persistent storage for wallets, assets, and the conditional locks
+//! (HTLCs, mail escrows, oracle-settled contracts) that freeze funds on
+//! top of them.
+
+#![allow(bare_trait_objects)]
+
+use exonum::crypto::{hash, Hash, PublicKey};
+use exonum::storage::{Fork, MapIndex, ProofListIndex, ProofMapIndex, Snapshot};
+
+use transactions::{native_token, ConditionalOutcome, TokenId};
+
+encoding_struct! {
+    /// A wallet's name. Both the spendable and frozen balances live in
+    /// separate per-`(pub_key, token_id)` indexes, since a wallet may hold
+    /// and freeze more than one asset at once.
+    struct Wallet {
+        pub_key: &PublicKey,
+        name: &str,
+    }
+}
+
+encoding_struct! {
+    /// An asset registered by `IssueAsset`, identified elsewhere by the
+    /// `TokenId` derived from `issuer_key` and `ticker`.
+    struct Asset {
+        issuer_key: &PublicKey,
+        ticker: &str,
+        decimals: u32,
+        total_supply: u64,
+    }
+}
+
+encoding_struct! {
+    /// A hash-time-locked contract created by `LockHtlc`.
+    struct Htlc {
+        tx_hash: &Hash,
+        from: &PublicKey,
+        to: &PublicKey,
+        amount: u64,
+        hash_lock: &Hash,
+        expiry: u64,
+        settled: bool,
+    }
+}
+
+encoding_struct! {
+    /// A mail escrow created by `MailPreparation`, settled by either a
+    /// `MailAcceptance` or an `ExpireMail`.
+    struct Escrow {
+        tx_hash: &Hash,
+        sender: &PublicKey,
+        to: &PublicKey,
+        amount: u64,
+        token_id: &Hash,
+        meta: &str,
+        deadline: u64,
+        settled: bool,
+    }
+}
+
+encoding_struct! {
+    /// How much a wallet has withdrawn from the faucet during the window
+    /// starting at `window_start`.
+    struct FaucetWindow {
+        window_start: u64,
+        withdrawn: u64,
+    }
+}
+
+encoding_struct! {
+    /// A single oracle-decidable outcome stored inside a `Conditional`.
+    struct Outcome {
+        label: &str,
+        payout_a: u64,
+        payout_b: u64,
+    }
+}
+
+encoding_struct! {
+    /// An oracle-attested conditional contract created by `CreateConditional`.
+    struct Conditional {
+        funder: &PublicKey,
+        party_a: &PublicKey,
+        party_b: &PublicKey,
+        oracle: &PublicKey,
+        amount: u64,
+        token_id: &Hash,
+        outcomes: Vec<Outcome>,
+        settled: bool,
+    }
+}
+
+/// Combines a wallet's public key and a token id into a single hashable key,
+/// since `ProofMapIndex` keys must be a single fixed-size value and a wallet
+/// may hold balances of more than one asset.
+fn balance_key(pub_key: &PublicKey, token_id: &TokenId) -> Hash {
+    let mut bytes = pub_key.as_ref().to_vec();
+    bytes.extend_from_slice(token_id.as_ref());
+    hash(&bytes)
+}
+
+#[derive(Debug)]
+pub struct CurrencySchema<T> {
+    view: T,
+}
+
+impl<T: AsRef<Snapshot>> CurrencySchema<T> {
+    pub fn new(view: T) -> Self {
+        CurrencySchema { view }
+    }
+
+    pub fn wallets(&self) -> ProofMapIndex<&Snapshot, PublicKey, Wallet> {
+        ProofMapIndex::new("cryptocurrency.wallets", self.view.as_ref())
+    }
+
+    pub fn balances(&self) -> ProofMapIndex<&Snapshot, Hash, u64> {
+        ProofMapIndex::new("cryptocurrency.balances", self.view.as_ref())
+    }
+
+    pub fn frozen_balances(&self) -> ProofMapIndex<&Snapshot, Hash, u64> {
+        ProofMapIndex::new("cryptocurrency.frozen_balances", self.view.as_ref())
+    }
+
+    pub fn assets(&self) -> ProofMapIndex<&Snapshot, Hash, Asset> {
+        ProofMapIndex::new("cryptocurrency.assets", self.view.as_ref())
+    }
+
+    pub fn asset_tickers(&self) -> MapIndex<&Snapshot, String, Hash> {
+        MapIndex::new("cryptocurrency.asset_tickers", self.view.as_ref())
+    }
+
+    pub fn htlcs(&self) -> ProofMapIndex<&Snapshot, Hash, Htlc> {
+        ProofMapIndex::new("cryptocurrency.htlcs", self.view.as_ref())
+    }
+
+    pub fn escrows(&self) -> ProofMapIndex<&Snapshot, Hash, Escrow> {
+        ProofMapIndex::new("cryptocurrency.escrows", self.view.as_ref())
+    }
+
+    pub fn conditionals(&self) -> ProofMapIndex<&Snapshot, Hash, Conditional> {
+        ProofMapIndex::new("cryptocurrency.conditionals", self.view.as_ref())
+    }
+
+    pub fn faucet_windows(&self) -> MapIndex<&Snapshot, PublicKey, FaucetWindow> {
+        MapIndex::new("cryptocurrency.faucet_windows", self.view.as_ref())
+    }
+
+    fn wallet_history_index(&self, pub_key: &PublicKey) -> ProofListIndex<&Snapshot, Hash> {
+        ProofListIndex::new_in_family("cryptocurrency.wallet_history", pub_key, self.view.as_ref())
+    }
+
+    pub fn wallet(&self, pub_key: &PublicKey) -> Option<Wallet> {
+        self.wallets().get(pub_key)
+    }
+
+    pub fn wallet_balance(&self, pub_key: &PublicKey, token_id: &TokenId) -> u64 {
+        self.balances().get(&balance_key(pub_key, token_id)).unwrap_or(0)
+    }
+
+    pub fn wallet_frozen_balance(&self, pub_key: &PublicKey, token_id: &TokenId) -> u64 {
+        self.frozen_balances()
+            .get(&balance_key(pub_key, token_id))
+            .unwrap_or(0)
+    }
+
+    pub fn asset(&self, token_id: &TokenId) -> Option<Asset> {
+        self.assets().get(token_id)
+    }
+
+    pub fn asset_by_ticker(&self, ticker: &str) -> Option<Asset> {
+        self.asset_tickers()
+            .get(&ticker.to_owned())
+            .and_then(|token_id| self.asset(&token_id))
+    }
+
+    pub fn htlc(&self, tx_hash: &Hash) -> Option<Htlc> {
+        self.htlcs().get(tx_hash)
+    }
+
+    pub fn escrow(&self, tx_hash: &Hash) -> Option<Escrow> {
+        self.escrows().get(tx_hash)
+    }
+
+    pub fn conditional(&self, tx_hash: &Hash) -> Option<Conditional> {
+        self.conditionals().get(tx_hash)
+    }
+
+    pub fn faucet_window(&self, pub_key: &PublicKey) -> Option<FaucetWindow> {
+        self.faucet_windows().get(pub_key)
+    }
+
+    pub fn wallet_history(&self, pub_key: &PublicKey) -> Vec<Hash> {
+        self.wallet_history_index(pub_key).iter().collect()
+    }
+
+    pub fn pending_escrows(&self, pub_key: &PublicKey) -> Vec<Escrow> {
+        self.escrows()
+            .values()
+            .filter(|escrow| !escrow.settled() && (escrow.sender() == pub_key || escrow.to() == pub_key))
+            .collect()
+    }
+
+    pub fn pending_htlcs(&self, pub_key: &PublicKey) -> Vec<Htlc> {
+        self.htlcs()
+            .values()
+            .filter(|htlc| !htlc.settled() && (htlc.from() == pub_key || htlc.to() == pub_key))
+            .collect()
+    }
+
+    pub fn state_hash(&self) -> Vec<Hash> {
+        vec![
+            self.wallets().merkle_root(),
+            self.balances().merkle_root(),
+            self.frozen_balances().merkle_root(),
+            self.assets().merkle_root(),
+            self.htlcs().merkle_root(),
+            self.escrows().merkle_root(),
+            self.conditionals().merkle_root(),
+        ]
+    }
+}
+
+impl<'a> CurrencySchema<&'a mut Fork> {
+    fn wallets_mut(&mut self) -> ProofMapIndex<&mut Fork, PublicKey, Wallet> {
+        ProofMapIndex::new("cryptocurrency.wallets", &mut self.view)
+    }
+
+    fn balances_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, u64> {
+        ProofMapIndex::new("cryptocurrency.balances", &mut self.view)
+    }
+
+    fn frozen_balances_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, u64> {
+        ProofMapIndex::new("cryptocurrency.frozen_balances", &mut self.view)
+    }
+
+    fn assets_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Asset> {
+        ProofMapIndex::new("cryptocurrency.assets", &mut self.view)
+    }
+
+    fn asset_tickers_mut(&mut self) -> MapIndex<&mut Fork, String, Hash> {
+        MapIndex::new("cryptocurrency.asset_tickers", &mut self.view)
+    }
+
+    fn htlcs_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Htlc> {
+        ProofMapIndex::new("cryptocurrency.htlcs", &mut self.view)
+    }
+
+    fn escrows_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Escrow> {
+        ProofMapIndex::new("cryptocurrency.escrows", &mut self.view)
+    }
+
+    fn conditionals_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Conditional> {
+        ProofMapIndex::new("cryptocurrency.conditionals", &mut self.view)
+    }
+
+    fn faucet_windows_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, FaucetWindow> {
+        MapIndex::new("cryptocurrency.faucet_windows", &mut self.view)
+    }
+
+    fn wallet_history_mut(&mut self, pub_key: &PublicKey) -> ProofListIndex<&mut Fork, Hash> {
+        ProofListIndex::new_in_family("cryptocurrency.wallet_history", pub_key, &mut self.view)
+    }
+
+    fn append_history(&mut self, pub_key: &PublicKey, tx_hash: &Hash) {
+        self.wallet_history_mut(pub_key).push(*tx_hash);
+    }
+
+    /// Releases `amount` of `token_id` from `pub_key`'s frozen balance, e.g.
+    /// once a lock that froze it has been settled one way or another.
+    fn release_frozen(&mut self, pub_key: &PublicKey, token_id: &TokenId, amount: u64) {
+        let key = balance_key(pub_key, token_id);
+        let frozen_balance = self.wallet_frozen_balance(pub_key, token_id).saturating_sub(amount);
+        self.frozen_balances_mut().put(&key, frozen_balance);
+    }
+
+    pub fn create_wallet(&mut self, pub_key: &PublicKey, name: &str, tx_hash: &Hash) {
+        self.wallets_mut().put(pub_key, Wallet::new(pub_key, name));
+        self.append_history(pub_key, tx_hash);
+    }
+
+    /// Credits `amount` of `token_id` to `wallet`, optionally also adding
+    /// `frozen_delta` to its frozen balance of that same token.
+    pub fn increase_wallet_balance(
+        &mut self,
+        wallet: Wallet,
+        amount: u64,
+        tx_hash: &Hash,
+        frozen_delta: u64,
+        token_id: &TokenId,
+    ) {
+        let pub_key = *wallet.pub_key();
+        let key = balance_key(&pub_key, token_id);
+        let new_balance = self.wallet_balance(&pub_key, token_id) + amount;
+        self.balances_mut().put(&key, new_balance);
+
+        if frozen_delta != 0 {
+            let frozen_balance = self.wallet_frozen_balance(&pub_key, token_id) + frozen_delta;
+            self.frozen_balances_mut().put(&key, frozen_balance);
+        }
+
+        self.append_history(&pub_key, tx_hash);
+    }
+
+    /// Debits `amount` of `token_id` from `wallet`, optionally also adding
+    /// `frozen_delta` to its frozen balance of that same token (used to
+    /// freeze the debited funds pending a lock's settlement, e.g. an HTLC or
+    /// escrow).
+    pub fn decrease_wallet_balance(
+        &mut self,
+        wallet: Wallet,
+        amount: u64,
+        tx_hash: &Hash,
+        frozen_delta: u64,
+        token_id: &TokenId,
+    ) {
+        let pub_key = *wallet.pub_key();
+        let key = balance_key(&pub_key, token_id);
+        let new_balance = self.wallet_balance(&pub_key, token_id) - amount;
+        self.balances_mut().put(&key, new_balance);
+
+        if frozen_delta != 0 {
+            let frozen_balance = self.wallet_frozen_balance(&pub_key, token_id) + frozen_delta;
+            self.frozen_balances_mut().put(&key, frozen_balance);
+        }
+
+        self.append_history(&pub_key, tx_hash);
+    }
+
+    pub fn create_asset(
+        &mut self,
+        token_id: &TokenId,
+        issuer_key: &PublicKey,
+        ticker: &str,
+        decimals: u32,
+        total_supply: u64,
+        _tx_hash: &Hash,
+    ) {
+        self.assets_mut()
+            .put(token_id, Asset::new(issuer_key, ticker, decimals, total_supply));
+        self.asset_tickers_mut().put(&ticker.to_owned(), *token_id);
+    }
+
+    pub fn lock_htlc(
+        &mut self,
+        tx_hash: &Hash,
+        from: &PublicKey,
+        to: &PublicKey,
+        amount: u64,
+        hash_lock: &Hash,
+        expiry: u64,
+    ) {
+        self.htlcs_mut().put(
+            tx_hash,
+            Htlc::new(tx_hash, from, to, amount, hash_lock, expiry, false),
+        );
+    }
+
+    pub fn settle_htlc(&mut self, tx_hash: &Hash) {
+        if let Some(htlc) = self.htlc(tx_hash) {
+            // HTLCs only ever lock the native token.
+            self.release_frozen(htlc.from(), &native_token(), htlc.amount());
+            self.htlcs_mut().put(
+                tx_hash,
+                Htlc::new(
+                    htlc.tx_hash(),
+                    htlc.from(),
+                    htlc.to(),
+                    htlc.amount(),
+                    htlc.hash_lock(),
+                    htlc.expiry(),
+                    true,
+                ),
+            );
+        }
+    }
+
+    pub fn lock_escrow(
+        &mut self,
+        tx_hash: &Hash,
+        sender: &PublicKey,
+        to: &PublicKey,
+        amount: u64,
+        token_id: &TokenId,
+        meta: &str,
+        deadline: u64,
+    ) {
+        self.escrows_mut().put(
+            tx_hash,
+            Escrow::new(tx_hash, sender, to, amount, token_id, meta, deadline, false),
+        );
+    }
+
+    pub fn settle_escrow(&mut self, tx_hash: &Hash) {
+        if let Some(escrow) = self.escrow(tx_hash) {
+            self.release_frozen(escrow.sender(), escrow.token_id(), escrow.amount());
+            self.escrows_mut().put(
+                tx_hash,
+                Escrow::new(
+                    escrow.tx_hash(),
+                    escrow.sender(),
+                    escrow.to(),
+                    escrow.amount(),
+                    escrow.token_id(),
+                    escrow.meta(),
+                    escrow.deadline(),
+                    true,
+                ),
+            );
+        }
+    }
+
+    pub fn set_faucet_window(&mut self, pub_key: &PublicKey, window_start: u64, withdrawn: u64) {
+        self.faucet_windows_mut()
+            .put(pub_key, FaucetWindow::new(window_start, withdrawn));
+    }
+
+    pub fn lock_conditional(
+        &mut self,
+        tx_hash: &Hash,
+        funder: &PublicKey,
+        party_a: &PublicKey,
+        party_b: &PublicKey,
+        oracle: &PublicKey,
+        amount: u64,
+        token_id: &TokenId,
+        outcomes: &[ConditionalOutcome],
+    ) {
+        let outcomes = outcomes
+            .iter()
+            .map(|outcome| Outcome::new(&outcome.label, outcome.payout_a, outcome.payout_b))
+            .collect();
+        self.conditionals_mut().put(
+            tx_hash,
+            Conditional::new(funder, party_a, party_b, oracle, amount, token_id, outcomes, false),
+        );
+    }
+
+    pub fn settle_conditional(&mut self, tx_hash: &Hash) {
+        if let Some(contract) = self.conditional(tx_hash) {
+            self.release_frozen(contract.funder(), contract.token_id(), contract.amount());
+            self.conditionals_mut().put(
+                tx_hash,
+                Conditional::new(
+                    contract.funder(),
+                    contract.party_a(),
+                    contract.party_b(),
+                    contract.oracle(),
+                    contract.amount(),
+                    contract.token_id(),
+                    contract.outcomes(),
+                    true,
+                ),
+            );
+        }
+    }
+}
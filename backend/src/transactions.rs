@@ -19,21 +19,53 @@ extern crate serde_json;
 extern crate serde;
 
 
-use serde::{Deserialize, Serialize, Deserializer, Serializer};
+use serde::{Deserialize, Serialize};
 
-use exonum::blockchain::{ExecutionError, ExecutionResult, Transaction};
-use exonum::crypto::{CryptoHash, PublicKey, Hash, gen_keypair};
+use exonum::blockchain::{self, ExecutionError, ExecutionResult, Transaction, TransactionSet};
+use exonum::crypto::{self, CryptoHash, PublicKey, Hash, Signature, gen_keypair};
 use exonum::messages::Message;
 use exonum::storage::Fork;
 use exonum::storage::StorageValue;
 use exonum::messages::RawMessage;
 use exonum::storage::Snapshot;
+use exonum::proto::ProtobufConvert;
 //use exonum::messages::Message::from_raw;
-use exonum::explorer::TransactionInfo;
 
 
-use CRYPTOCURRENCY_SERVICE_ID;
 use schema::CurrencySchema;
+use proto;
+
+/// Identifier of an asset, including the built-in native currency (the zero
+/// hash). Any other asset's id is derived deterministically from the issuer's
+/// public key and the asset's ticker.
+pub type TokenId = Hash;
+
+/// `TokenId` of the chain's native currency, used by transactions that don't
+/// name an asset explicitly.
+pub fn native_token() -> TokenId {
+    Hash::zero()
+}
+
+/// Derives the deterministic `TokenId` of an asset from its issuer and ticker.
+fn token_id(issuer_key: &PublicKey, ticker: &str) -> TokenId {
+    let mut bytes = issuer_key.as_ref().to_vec();
+    bytes.extend_from_slice(ticker.as_bytes());
+    crypto::hash(&bytes)
+}
+
+/// Length, in blocks, of a single faucet withdrawal window.
+const FAUCET_WINDOW_LENGTH: u64 = 1_440;
+
+/// Maximum amount (in the native currency's smallest unit) a single wallet
+/// may withdraw from the faucet within one window, e.g. 10_000 base units
+/// with 2 decimals is a limit of "100" display units.
+const FAUCET_WITHDRAWAL_LIMIT: u64 = 10_000;
+
+/// Returns the deterministic start height of the faucet withdrawal window
+/// that `height` falls into, so every validator agrees on window boundaries.
+fn faucet_window_start(height: u64) -> u64 {
+    height - height % FAUCET_WINDOW_LENGTH
+}
 
 
 /// Error codes emitted by wallet transactions during execution.
@@ -63,6 +95,123 @@ pub enum Error {
     /// Can be emitted by `Transfer`.
     #[fail(display = "Insufficient currency amount")]
     InsufficientCurrencyAmount = 3,
+
+    /// HTLC with the given hash doesn't exist.
+    ///
+    /// Can be emitted by `RedeemHtlc` or `RefundHtlc`.
+    #[fail(display = "HTLC not found")]
+    HtlcNotFound = 4,
+
+    /// The provided preimage doesn't hash to the committed `hash_lock`.
+    ///
+    /// Can be emitted by `RedeemHtlc`.
+    #[fail(display = "Invalid preimage")]
+    InvalidPreimage = 5,
+
+    /// The current height hasn't passed the HTLC's `expiry` height yet.
+    ///
+    /// Can be emitted by `RefundHtlc`.
+    #[fail(display = "HTLC is not expired yet")]
+    HtlcNotExpired = 6,
+
+    /// The HTLC has already been redeemed or refunded.
+    ///
+    /// Can be emitted by `RedeemHtlc` or `RefundHtlc`.
+    #[fail(display = "HTLC is already settled")]
+    HtlcAlreadySettled = 7,
+
+    /// An asset with the given ticker has already been issued, by any issuer.
+    ///
+    /// Can be emitted by `IssueAsset`.
+    #[fail(display = "Asset already exists")]
+    AssetAlreadyExists = 8,
+
+    /// Escrow with the given prep transaction hash doesn't exist.
+    ///
+    /// Can be emitted by `MailAcceptance` or `ExpireMail`.
+    #[fail(display = "Escrow not found")]
+    EscrowNotFound = 9,
+
+    /// The escrow has already been accepted, rejected, or expired.
+    ///
+    /// Can be emitted by `MailAcceptance` or `ExpireMail`.
+    #[fail(display = "Escrow is already settled")]
+    EscrowAlreadySettled = 10,
+
+    /// The escrow's deadline doesn't allow the requested operation: it has
+    /// already passed for `MailAcceptance`, or hasn't passed yet for
+    /// `ExpireMail`.
+    #[fail(display = "Escrow deadline forbids this operation")]
+    EscrowExpired = 11,
+
+    /// The withdrawal would take the wallet's faucet total for the current
+    /// window over the limit.
+    ///
+    /// Can be emitted by `FaucetWithdraw`.
+    #[fail(display = "Faucet withdrawal limit exceeded")]
+    FaucetLimitExceeded = 12,
+
+    /// Conditional contract with the given hash doesn't exist.
+    ///
+    /// Can be emitted by `SettleConditional`.
+    #[fail(display = "Conditional contract not found")]
+    ConditionalNotFound = 13,
+
+    /// The given outcome isn't one of the contract's committed outcomes.
+    ///
+    /// Can be emitted by `SettleConditional`.
+    #[fail(display = "Unknown conditional outcome")]
+    UnknownOutcome = 14,
+
+    /// `oracle_sig` isn't a valid signature by the contract's oracle over
+    /// the outcome label.
+    ///
+    /// Can be emitted by `SettleConditional`.
+    #[fail(display = "Invalid oracle signature")]
+    InvalidOracleSignature = 15,
+
+    /// The conditional contract has already been settled.
+    ///
+    /// Can be emitted by `SettleConditional`.
+    #[fail(display = "Conditional contract is already settled")]
+    ConditionalAlreadySettled = 16,
+
+    /// The current height has already passed the HTLC's `expiry`, so the funds are
+    /// reserved for the sender's `RefundHtlc` instead.
+    ///
+    /// Can be emitted by `RedeemHtlc`.
+    #[fail(display = "HTLC has already expired")]
+    HtlcExpired = 17,
+
+    /// Only the escrow's recipient may accept or reject it.
+    ///
+    /// Can be emitted by `MailAcceptance`.
+    #[fail(display = "Only the escrow's recipient may accept or reject it")]
+    NotEscrowRecipient = 18,
+
+    /// No asset with the given `token_id` has been registered.
+    ///
+    /// Can be emitted by `Issue`.
+    #[fail(display = "Asset not found")]
+    AssetNotFound = 19,
+
+    /// Only the asset's registered issuer may mint more of it.
+    ///
+    /// Can be emitted by `Issue`.
+    #[fail(display = "Only the asset's registered issuer may mint it")]
+    NotAssetIssuer = 20,
+
+    /// No wallet transaction with the given hash has been committed.
+    ///
+    /// Can be emitted by `Cancellation`.
+    #[fail(display = "Transaction not found")]
+    TransactionNotFound = 21,
+
+    /// Only the original transaction's sender may cancel it.
+    ///
+    /// Can be emitted by `Cancellation`.
+    #[fail(display = "Only the original sender may cancel this transaction")]
+    NotTransactionSender = 22,
 }
 
 impl From<Error> for ExecutionError {
@@ -72,69 +221,216 @@ impl From<Error> for ExecutionError {
     }
 }
 
-transactions! {
-    pub WalletTransactions {
-        const SERVICE_ID = CRYPTOCURRENCY_SERVICE_ID;
+/// Transfer `amount` of the `token_id` asset from one wallet to another.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::Transfer")]
+pub struct Transfer {
+    pub from: PublicKey,
+    pub to: PublicKey,
+    pub amount: u64,
+    pub token_id: Hash,
+    pub seed: u64,
+}
 
-        /// Transfer `amount` of the currency from one wallet to another.
-        struct Transfer {
-            from:    &PublicKey,
-            to:      &PublicKey,
-            amount:  u64,
-            seed:    u64,
-        }
+/// Issue `amount` of the `token_id` asset to the `pub_key` wallet.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::Issue")]
+pub struct Issue {
+    pub pub_key: PublicKey,
+    pub issuer_key: PublicKey,
+    pub amount: u64,
+    pub token_id: Hash,
+    pub seed: u64,
+}
 
-        /// Issue `amount` of the currency to the `wallet`.
-        struct Issue {
-            pub_key:  &PublicKey,
-            issuer_key: &PublicKey,
-            amount:  u64,
-            seed:    u64,
-        }
+/// Create wallet with the given `name`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::CreateWallet")]
+pub struct CreateWallet {
+    pub pub_key: PublicKey,
+    pub name: String,
+}
 
-        /// Create wallet with the given `name`.
-        struct CreateWallet {
-            pub_key: &PublicKey,
-            name:    &str,
-        }
+/// Mint a brand new asset identified by `ticker`, denominated with `decimals`
+/// digits of precision, crediting `initial_supply` to the issuer.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::IssueAsset")]
+pub struct IssueAsset {
+    pub issuer_key: PublicKey,
+    pub ticker: String,
+    pub decimals: u32,
+    pub initial_supply: u64,
+    pub seed: u64,
+}
 
-        struct MailPreparation {
-            meta: &str,
-            pub_key: &PublicKey,
-            amount: u64,
-            seed: u64,
-        }
+/// Freeze `amount` of `token_id`, owed to `to`, pending a `MailAcceptance`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::MailPreparation")]
+pub struct MailPreparation {
+    pub meta: String,
+    pub pub_key: PublicKey,
+    pub to: PublicKey,
+    pub amount: u64,
+    pub token_id: Hash,
+    pub deadline: u64,
+    pub seed: u64,
+}
 
-        struct MailAcceptance {
-            sender: &PublicKey,
-            pub_key: &PublicKey,
-            amount: u64,
-            accept:  bool,
-            seed: u64,
-        }
-        
-        struct Cancellation {
-            pub_key: &PublicKey,
-            sender: &PublicKey,
-            tx_hash: &Hash,
-            type_transaction: u64,
-        }
-    }
+/// Accept or reject the pending escrow created by the `MailPreparation`
+/// identified by `prep_tx_hash`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::MailAcceptance")]
+pub struct MailAcceptance {
+    pub pub_key: PublicKey,
+    pub prep_tx_hash: Hash,
+    pub accept: bool,
+    pub seed: u64,
+}
+
+/// Reclaim the funds of an escrow whose deadline has passed without an
+/// accept/reject decision.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::ExpireMail")]
+pub struct ExpireMail {
+    pub sender: PublicKey,
+    pub prep_tx_hash: Hash,
+}
+
+/// Withdraw `amount` of the native currency from the testnet faucet, subject
+/// to a rate limit enforced per withdrawal window.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::FaucetWithdraw")]
+pub struct FaucetWithdraw {
+    pub pub_key: PublicKey,
+    pub amount: u64,
+    pub seed: u64,
+}
+
+/// A single oracle-decidable outcome of a `CreateConditional` contract, and
+/// how the locked amount splits between `party_a` and `party_b` if it occurs.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::ConditionalOutcome")]
+pub struct ConditionalOutcome {
+    pub label: String,
+    pub payout_a: u64,
+    pub payout_b: u64,
+}
+
+/// Lock `amount` into an oracle-attested conditional contract between
+/// `party_a` and `party_b`, to be settled by `oracle`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::CreateConditional")]
+pub struct CreateConditional {
+    pub funder: PublicKey,
+    pub party_a: PublicKey,
+    pub party_b: PublicKey,
+    pub oracle: PublicKey,
+    pub amount: u64,
+    pub token_id: Hash,
+    pub outcomes: Vec<ConditionalOutcome>,
+    pub seed: u64,
+}
+
+/// Settle a `CreateConditional` contract for the given `outcome`, attested by
+/// `oracle_sig` over the outcome label.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::SettleConditional")]
+pub struct SettleConditional {
+    pub contract_hash: Hash,
+    pub outcome: String,
+    pub oracle_sig: Signature,
+}
+
+/// Revert a previously committed `Transfer` identified by `tx_hash`. A
+/// `Cancellation` pointing at any other kind of transaction changes nothing.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::Cancellation")]
+pub struct Cancellation {
+    pub pub_key: PublicKey,
+    pub sender: PublicKey,
+    pub tx_hash: Hash,
+    pub type_transaction: u64,
+}
+
+/// Lock `amount` of the currency into a hash-time-locked contract, to be
+/// redeemed by `to` with the `hash_lock` preimage or reclaimed by `from`
+/// after `expiry`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::LockHtlc")]
+pub struct LockHtlc {
+    pub from: PublicKey,
+    pub to: PublicKey,
+    pub amount: u64,
+    pub hash_lock: Hash,
+    pub expiry: u64,
+    pub seed: u64,
+}
+
+/// Redeem a previously locked HTLC by revealing the `preimage` of its
+/// `hash_lock`.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::RedeemHtlc")]
+pub struct RedeemHtlc {
+    pub redeemer: PublicKey,
+    pub htlc_hash: Hash,
+    pub preimage: Vec<u8>,
+}
+
+/// Reclaim the funds of an expired HTLC back to its sender.
+#[derive(Serialize, Deserialize, Clone, Debug, ProtobufConvert)]
+#[exonum(pb = "proto::RefundHtlc")]
+pub struct RefundHtlc {
+    pub sender: PublicKey,
+    pub htlc_hash: Hash,
+}
+
+/// Every transaction the cryptocurrency service accepts, tagged by a
+/// `message_id` so that a stored transaction can be decoded back to its
+/// concrete variant instead of being blindly cast.
+#[derive(Serialize, Deserialize, Clone, Debug, TransactionSet)]
+pub enum WalletTransactions {
+    Transfer(Transfer),
+    Issue(Issue),
+    CreateWallet(CreateWallet),
+    IssueAsset(IssueAsset),
+    MailPreparation(MailPreparation),
+    MailAcceptance(MailAcceptance),
+    Cancellation(Cancellation),
+    LockHtlc(LockHtlc),
+    RedeemHtlc(RedeemHtlc),
+    RefundHtlc(RefundHtlc),
+    ExpireMail(ExpireMail),
+    FaucetWithdraw(FaucetWithdraw),
+    CreateConditional(CreateConditional),
+    SettleConditional(SettleConditional),
 }
 
 impl Transaction for Issue {
     fn verify(&self) -> bool {
-        self.verify_signature(self.issuer_key())
+        self.verify_signature(&self.issuer_key)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
         let mut schema = CurrencySchema :: new(fork);
-        let pub_key = self.pub_key();
+        let pub_key = &self.pub_key;
         let hash = self.hash();
+        let token_id = &self.token_id;
+
+        // The native token has no registry entry, so the legacy behavior of
+        // letting a caller self-issue it still applies. Any other token_id
+        // belongs to whichever issuer registered it via `IssueAsset`, so
+        // minting more of it requires that registered key, not just any
+        // signature.
+        if *token_id != native_token() {
+            let asset = schema.asset(token_id).ok_or(Error::AssetNotFound)?;
+            if asset.issuer_key() != &self.issuer_key {
+                Err(Error::NotAssetIssuer)?;
+            }
+        }
 
         if let Some(wallet) = schema.wallet(pub_key) {
-            let amount = self.amount();
-            schema.increase_wallet_balance(wallet, amount, &hash, 0);
+            let amount = self.amount;
+            schema.increase_wallet_balance(wallet, amount, &hash, 0, token_id);
             Ok(())
         } else {
             Err(Error::ReceiverNotFound)?
@@ -143,30 +439,56 @@ impl Transaction for Issue {
 
 }
 
+impl Transaction for IssueAsset {
+    fn verify(&self) -> bool {
+        self.verify_signature(&self.issuer_key)
+    }
+
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let mut schema = CurrencySchema::new(fork);
+        let issuer_key = &self.issuer_key;
+        let ticker = &self.ticker;
+        let hash = self.hash();
+
+        if schema.asset_by_ticker(ticker).is_some() {
+            Err(Error::AssetAlreadyExists)?;
+        }
+
+        let id = token_id(issuer_key, ticker);
+        let issuer = schema.wallet(issuer_key).ok_or(Error::SenderNotFound)?;
+
+        schema.create_asset(&id, issuer_key, ticker, self.decimals, self.initial_supply, &hash);
+        schema.increase_wallet_balance(issuer, self.initial_supply, &hash, 0, &id);
+
+        Ok(())
+    }
+}
+
 
 impl Transaction for Transfer {
     fn verify(&self) -> bool {
-        (self.from() != self.to()) && self.verify_signature(self.from())
+        (self.from != self.to) && self.verify_signature(&self.from)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
         let mut schema = CurrencySchema::new(fork);
-        let from = self.from();
-        let to = self.to();
+        let from = &self.from;
+        let to = &self.to;
         let hash = self.hash();
-        let amount = self.amount();
+        let amount = self.amount;
+        let token_id = &self.token_id;
         let freezed_balance = 0;
         let sender = schema.wallet(from).ok_or(Error :: SenderNotFound)?;
 
         let receiver = schema.wallet(to).ok_or(Error :: ReceiverNotFound)?;
 
-        if sender.balance() < amount {
+        if schema.wallet_balance(from, token_id) < amount {
             Err(Error::InsufficientCurrencyAmount)?;
 
         }
 
-        schema.decrease_wallet_balance(sender, amount, &hash, freezed_balance);
-        schema.increase_wallet_balance(receiver, amount, &hash, freezed_balance);
+        schema.decrease_wallet_balance(sender, amount, &hash, freezed_balance, token_id);
+        schema.increase_wallet_balance(receiver, amount, &hash, freezed_balance, token_id);
 
         Ok(())
     }
@@ -174,42 +496,46 @@ impl Transaction for Transfer {
 
 impl Transaction for CreateWallet {
     fn verify(&self) -> bool {
-        self.verify_signature(self.pub_key())
+        self.verify_signature(&self.pub_key)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
         let mut schema = CurrencySchema::new(fork);
-        let pub_key = self.pub_key();
+        let pub_key = &self.pub_key;
         let hash = self.hash();
 
         if schema.wallet(pub_key).is_none(){
-            let name = self.name();
-            let freezed_balance = 0;
-            schema.create_wallet(pub_key, name, &hash, freezed_balance);
+            let name = &self.name;
+            schema.create_wallet(pub_key, name, &hash);
             Ok(())
         } else {
             Err(Error::WalletAlreadyExists)?
-        } 
-    }    
+        }
+    }
 }
 
 
 impl Transaction for MailPreparation {
     fn verify(&self) -> bool {
-        self.verify_signature(self.pub_key())
+        (self.pub_key != self.to) && self.verify_signature(&self.pub_key)
     }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
         let mut schema = CurrencySchema :: new(fork);
-        let pub_key = self.pub_key();
-        let amount = self.amount();
+        let pub_key = &self.pub_key;
+        let to = &self.to;
+        let amount = self.amount;
         let hash = self.hash();
+        let token_id = &self.token_id;
         let sender = schema.wallet(pub_key).ok_or(Error :: SenderNotFound)?;
-        if sender.balance() < amount {
+        schema.wallet(to).ok_or(Error :: ReceiverNotFound)?;
+        if schema.wallet_balance(pub_key, token_id) < amount {
             Err(Error::InsufficientCurrencyAmount)?;
         }
-        // freeze_wallet_balance rrealize
-        schema.decrease_wallet_balance(sender, amount, &hash, amount);
+        // Freeze the funds so they can't be spent by a concurrent `Transfer`
+        // while the escrow is pending.
+        schema.decrease_wallet_balance(sender, amount, &hash, amount, token_id);
+        schema.lock_escrow(&hash, pub_key, to, amount, token_id, &self.meta, self.deadline);
         Ok(())
     }
 }
@@ -217,128 +543,332 @@ impl Transaction for MailPreparation {
 
 impl Transaction for MailAcceptance {
     fn verify(&self) -> bool {
-        self.verify_signature(self.pub_key())
+        self.verify_signature(&self.pub_key)
     }
 
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let height = blockchain::Schema::new(fork.as_ref()).height().0;
+
+        let mut schema = CurrencySchema :: new(fork);
+        let prep_tx_hash = &self.prep_tx_hash;
+        let hash = self.hash();
+
+        let escrow = schema.escrow(prep_tx_hash).ok_or(Error::EscrowNotFound)?;
+        if escrow.settled() {
+            Err(Error::EscrowAlreadySettled)?;
+        }
+        if height > escrow.deadline() {
+            Err(Error::EscrowExpired)?;
+        }
+        // Only the escrow's recipient may decide whether to accept or
+        // reject it; otherwise any wallet could force someone else's
+        // pending escrow to be refunded to the sender.
+        if &self.pub_key != escrow.to() {
+            Err(Error::NotEscrowRecipient)?;
+        }
+
+        if self.accept {
+            let recipient = schema.wallet(escrow.to()).ok_or(Error::ReceiverNotFound)?;
+            schema.increase_wallet_balance(recipient, escrow.amount(), &hash, 0, escrow.token_id());
+        } else {
+            let sender = schema.wallet(escrow.sender()).ok_or(Error::SenderNotFound)?;
+            schema.increase_wallet_balance(sender, escrow.amount(), &hash, 0, escrow.token_id());
+        }
+        schema.settle_escrow(prep_tx_hash);
+
+        Ok(())
+    }
+}
 
+impl Transaction for ExpireMail {
+    fn verify(&self) -> bool {
+        self.verify_signature(&self.sender)
+    }
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
-        let mut schema = CurrencySchema :: new(fork);
-        let sender_key = self.sender();
+        let height = blockchain::Schema::new(fork.as_ref()).height().0;
 
+        let mut schema = CurrencySchema :: new(fork);
+        let prep_tx_hash = &self.prep_tx_hash;
         let hash = self.hash();
-        let sender = schema.wallet(sender_key).ok_or(Error :: SenderNotFound)?;
-        let freezed_balance = 0;
-        schema.decrease_wallet_balance(sender, freezed_balance, &hash, freezed_balance);
-        Ok(())
 
+        let escrow = schema.escrow(prep_tx_hash).ok_or(Error::EscrowNotFound)?;
+        if escrow.settled() {
+            Err(Error::EscrowAlreadySettled)?;
+        }
+        if height <= escrow.deadline() {
+            Err(Error::EscrowExpired)?;
+        }
+
+        let sender = schema.wallet(escrow.sender()).ok_or(Error::SenderNotFound)?;
+        schema.increase_wallet_balance(sender, escrow.amount(), &hash, 0, escrow.token_id());
+        schema.settle_escrow(prep_tx_hash);
+
+        Ok(())
     }
 }
 
 impl Transaction for Cancellation {
     fn verify(&self) -> bool {
-        self.verify_signature(self.pub_key())
+        self.verify_signature(&self.pub_key)
     }
 
-    
+
 
     fn execute(&self, fork: &mut Fork) -> ExecutionResult {
-        let mut schema = CurrencySchema :: new(fork);
-        let sender_key = self.sender();
-        let tx_hash = self.tx_hash();
-        ///pub fn transaction(schema: &CurrencySchema<T>, tx_hash: &Hash) -> Option<Transaction> {
-        let raw_tx = schema.transactions().get(&tx_hash).unwrap();
-       //println!("transactions = {:?}", &raw_tx.body());
-        //let json = serde_json::to_value(&raw_tx.into_bytes()).unwrap();
-        //let info: Transfer = serde_json::from_value(json).unwrap();
-        let transaction: Transfer = Message::from_raw(raw_tx.clone()).unwrap();
-        //println!("transactions2 = {:?}", StorageValue :: from_bytes(t));
-
-        
-        
-        /*match raw_tx {
-            Some(v) => v,
-            None => Err(Error :: SenderNotFound)?,
-        };*/
-        //assert_eq!(raw_tx, None);
-
-        /*let content: Value = match serde_json::from_slice(&raw_tx.into_bytes()) {
-            Ok(r) => r,
-            Err(_er) => Err(Error :: ReceiverNotFound)?,
-        };
-        */
-        let id = self.type_transaction();
-        if id == 1 { //Transfer
-            let from = transaction.from();
-            let to = transaction.to();
-            let amount = transaction.amount();
-            let wallet_from = schema.wallet(&from).ok_or(Error :: SenderNotFound)?;
-            let wallet_to = schema.wallet(to).ok_or(Error :: ReceiverNotFound)?;
-            schema.decrease_wallet_balance(wallet_to, amount, &tx_hash, 0);
-            schema.increase_wallet_balance(wallet_from, amount, &tx_hash, 0);
-        }/* else if id == 2 { //issue
-            let pub_key = transaction.pub_key();
-            let amount = transaction.amount();
-            let sender = schema.wallet(pub_key).ok_or(Error :: ReceiverNotFound)?;
-            schema.decrease_wallet_balance(sender, amount, &tx_hash, 0);
-        }*/
+        let tx_hash = &self.tx_hash;
+        let raw_tx = blockchain::Schema::new(fork.as_ref())
+            .transactions()
+            .get(tx_hash)
+            .ok_or(Error::TransactionNotFound)?;
+
+        // Decode the stored message back into its tagged `WalletTransactions`
+        // variant instead of blindly casting it to `Transfer`, so a
+        // `Cancellation` can only ever reverse the kind of transaction it was
+        // actually pointed at.
+        let transaction =
+            WalletTransactions::tx_from_raw(raw_tx.clone()).map_err(|_| Error::TransactionNotFound)?;
+
+        let mut schema = CurrencySchema::new(fork);
+
+        if let WalletTransactions::Transfer(transaction) = transaction {
+            let from = transaction.from;
+            let to = transaction.to;
+            let amount = transaction.amount;
+            let token_id = &transaction.token_id;
+
+            // The caller must actually be the transaction's original sender,
+            // not just anyone who can produce a valid `Cancellation`
+            // signature: `self.pub_key` is who signed this message, and it
+            // must match both `self.sender` and the `Transfer`'s own `from`.
+            if self.pub_key != self.sender || self.sender != from {
+                Err(Error::NotTransactionSender)?;
+            }
+
+            let wallet_from = schema.wallet(&from).ok_or(Error::SenderNotFound)?;
+            let wallet_to = schema.wallet(&to).ok_or(Error::ReceiverNotFound)?;
+
+            if schema.wallet_balance(&to, token_id) < amount {
+                Err(Error::InsufficientCurrencyAmount)?;
+            }
+
+            schema.decrease_wallet_balance(wallet_to, amount, tx_hash, 0, token_id);
+            schema.increase_wallet_balance(wallet_from, amount, tx_hash, 0, token_id);
+        }
         Ok(())
+    }
+}
 
+impl Transaction for FaucetWithdraw {
+    fn verify(&self) -> bool {
+        self.verify_signature(&self.pub_key)
     }
 
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let window_start = faucet_window_start(blockchain::Schema::new(fork.as_ref()).height().0);
+
+        let mut schema = CurrencySchema::new(fork);
+        let pub_key = &self.pub_key;
+        let amount = self.amount;
+        let hash = self.hash();
+
+        let wallet = schema.wallet(pub_key).ok_or(Error::ReceiverNotFound)?;
+
+        let withdrawn_so_far = match schema.faucet_window(pub_key) {
+            Some(window) if window.window_start() == window_start => window.withdrawn(),
+            _ => 0,
+        };
+
+        // `checked_add` guards against an attacker picking an `amount` near
+        // `u64::MAX` to wrap the sum below the limit and defeat the rate
+        // limit entirely.
+        let withdrawn_total = withdrawn_so_far
+            .checked_add(amount)
+            .ok_or(Error::FaucetLimitExceeded)?;
+        if withdrawn_total > FAUCET_WITHDRAWAL_LIMIT {
+            Err(Error::FaucetLimitExceeded)?;
+        }
+
+        schema.increase_wallet_balance(wallet, amount, &hash, 0, &native_token());
+        schema.set_faucet_window(pub_key, window_start, withdrawn_total);
+
+        Ok(())
+    }
 }
-/*
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "kebab-case", bound(serialize = "T: SerializeContent"))]
-pub enum TransactionInfo<T = Box<dyn Transaction>> {
-    /// Transaction is in the memory pool, but not yet committed to the blockchain.
-    InPool {
-        /// Transaction contents.
-        #[serde(serialize_with = "SerializeContent::serialize_content")]
-        content: T,
-    },
-
-    /// Transaction is already committed to the blockchain.
-    Committed(CommittedTransaction<T>),
-}
-impl<T> TransactionInfo<T> {
-    /// Returns the content of this transaction.
-    pub fn content(&self) -> &T {
-        match *self {
-            TransactionInfo::InPool { ref content } => content,
-            TransactionInfo::Committed(ref tx) => tx.content(),
+
+impl Transaction for CreateConditional {
+    fn verify(&self) -> bool {
+        self.verify_signature(&self.funder)
+            && !self.outcomes.is_empty()
+            && self.outcomes.iter().all(|outcome| {
+                // `checked_add` guards against a funder picking payouts that
+                // overflow back down to `self.amount`, which would let
+                // `SettleConditional` mint far more than was ever frozen.
+                outcome
+                    .payout_a
+                    .checked_add(outcome.payout_b)
+                    .map_or(false, |total| total == self.amount)
+            })
+    }
+
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let mut schema = CurrencySchema::new(fork);
+        let funder = &self.funder;
+        let amount = self.amount;
+        let token_id = &self.token_id;
+        let hash = self.hash();
+
+        let sender = schema.wallet(funder).ok_or(Error::SenderNotFound)?;
+        schema.wallet(&self.party_a).ok_or(Error::ReceiverNotFound)?;
+        schema.wallet(&self.party_b).ok_or(Error::ReceiverNotFound)?;
+
+        if schema.wallet_balance(funder, token_id) < amount {
+            Err(Error::InsufficientCurrencyAmount)?;
         }
+
+        // Freeze the funds so they can't be spent by a concurrent `Transfer`
+        // while the contract is awaiting the oracle's attestation.
+        schema.decrease_wallet_balance(sender, amount, &hash, amount, token_id);
+        schema.lock_conditional(
+            &hash,
+            funder,
+            &self.party_a,
+            &self.party_b,
+            &self.oracle,
+            amount,
+            token_id,
+            &self.outcomes,
+        );
+
+        Ok(())
+    }
+}
+
+impl Transaction for SettleConditional {
+    fn verify(&self) -> bool {
+        // Anyone may relay the oracle's attestation; `execute` is what
+        // checks that `oracle_sig` was actually produced by the committed
+        // oracle key.
+        true
     }
 
-    /// Is this in-pool transaction?
-    pub fn is_in_pool(&self) -> bool {
-        match *self {
-            TransactionInfo::InPool { .. } => true,
-            _ => false,
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let mut schema = CurrencySchema::new(fork);
+        let contract_hash = &self.contract_hash;
+        let hash = self.hash();
+
+        let contract = schema.conditional(contract_hash).ok_or(Error::ConditionalNotFound)?;
+        if contract.settled() {
+            Err(Error::ConditionalAlreadySettled)?;
         }
+
+        if !crypto::verify(&self.oracle_sig, self.outcome.as_bytes(), contract.oracle()) {
+            Err(Error::InvalidOracleSignature)?;
+        }
+
+        let payout = contract
+            .outcomes()
+            .iter()
+            .find(|outcome| outcome.label() == &self.outcome)
+            .ok_or(Error::UnknownOutcome)?;
+
+        let party_a = schema.wallet(contract.party_a()).ok_or(Error::ReceiverNotFound)?;
+        schema.increase_wallet_balance(party_a, payout.payout_a(), &hash, 0, contract.token_id());
+
+        let party_b = schema.wallet(contract.party_b()).ok_or(Error::ReceiverNotFound)?;
+        schema.increase_wallet_balance(party_b, payout.payout_b(), &hash, 0, contract.token_id());
+
+        schema.settle_conditional(contract_hash);
+
+        Ok(())
     }
+}
 
-    /// Is this a committed transaction?
-    pub fn is_committed(&self) -> bool {
-        match *self {
-            TransactionInfo::Committed(_) => true,
-            _ => false,
+impl Transaction for LockHtlc {
+    fn verify(&self) -> bool {
+        (self.from != self.to) && self.verify_signature(&self.from)
+    }
+
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let mut schema = CurrencySchema::new(fork);
+        let from = &self.from;
+        let to = &self.to;
+        let amount = self.amount;
+        let hash = self.hash();
+
+        let sender = schema.wallet(from).ok_or(Error::SenderNotFound)?;
+        schema.wallet(to).ok_or(Error::ReceiverNotFound)?;
+
+        if schema.wallet_balance(from, &native_token()) < amount {
+            Err(Error::InsufficientCurrencyAmount)?;
         }
+
+        // Move `amount` into the sender's frozen balance so it can't be
+        // double-spent by a concurrent `Transfer` while the HTLC is pending.
+        schema.decrease_wallet_balance(sender, amount, &hash, amount, &native_token());
+        schema.lock_htlc(&hash, from, to, amount, &self.hash_lock, self.expiry);
+
+        Ok(())
     }
+}
 
-    /// Returns a reference to the inner committed transaction if this transaction is committed.
-    /// For transactions in pool, returns `None`.
-    pub fn as_committed(&self) -> Option<&CommittedTransaction<T>> {
-        match *self {
-            TransactionInfo::Committed(ref tx) => Some(tx),
-            _ => None,
+impl Transaction for RedeemHtlc {
+    fn verify(&self) -> bool {
+        self.verify_signature(&self.redeemer)
+    }
+
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let height = blockchain::Schema::new(fork.as_ref()).height().0;
+
+        let mut schema = CurrencySchema::new(fork);
+        let htlc_hash = &self.htlc_hash;
+        let hash = self.hash();
+
+        let htlc = schema.htlc(htlc_hash).ok_or(Error::HtlcNotFound)?;
+        if htlc.settled() {
+            Err(Error::HtlcAlreadySettled)?;
+        }
+        // A preimage holder may only redeem before the HTLC expires; past
+        // that height the funds are reserved for the sender's `RefundHtlc`.
+        if height > htlc.expiry() {
+            Err(Error::HtlcExpired)?;
         }
+        if &crypto::hash(&self.preimage) != htlc.hash_lock() {
+            Err(Error::InvalidPreimage)?;
+        }
+
+        let receiver = schema.wallet(htlc.to()).ok_or(Error::ReceiverNotFound)?;
+        schema.increase_wallet_balance(receiver, htlc.amount(), &hash, 0, &native_token());
+        schema.settle_htlc(htlc_hash);
+
+        Ok(())
     }
 }
 
-pub trait SerializeContent {
-    /// Serializes content of a transaction with the given serializer.
-    fn serialize_content<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer;
-}*/
\ No newline at end of file
+impl Transaction for RefundHtlc {
+    fn verify(&self) -> bool {
+        self.verify_signature(&self.sender)
+    }
+
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let height = blockchain::Schema::new(fork.as_ref()).height().0;
+
+        let mut schema = CurrencySchema::new(fork);
+        let htlc_hash = &self.htlc_hash;
+        let hash = self.hash();
+
+        let htlc = schema.htlc(htlc_hash).ok_or(Error::HtlcNotFound)?;
+        if htlc.settled() {
+            Err(Error::HtlcAlreadySettled)?;
+        }
+        if height <= htlc.expiry() {
+            Err(Error::HtlcNotExpired)?;
+        }
+
+        let sender = schema.wallet(htlc.from()).ok_or(Error::SenderNotFound)?;
+        schema.increase_wallet_balance(sender, htlc.amount(), &hash, 0, &native_token());
+        schema.settle_htlc(htlc_hash);
+
+        Ok(())
+    }
+}